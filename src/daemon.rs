@@ -0,0 +1,12 @@
+// Pacing for `--daemon` mode: a "tranquilizer" that keeps the fraction of
+// time spent probing near a target busy_fraction.
+
+use std::time::Duration;
+
+/// `sleep = batch_wall * (1 - p) / p`, clamped to `max_sleep` so a
+/// pathologically slow batch can't stall the daemon indefinitely.
+pub fn tranquilizer_sleep(batch_wall: Duration, busy_fraction: f64, max_sleep: Duration) -> Duration {
+    let p = busy_fraction.clamp(0.01, 1.0);
+    let sleep = batch_wall.as_secs_f64() * (1.0 - p) / p;
+    Duration::from_secs_f64(sleep.max(0.0)).min(max_sleep)
+}
@@ -0,0 +1,170 @@
+// Filesystem access abstracted behind a trait, for `--dry-run` and tests.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+pub trait Fs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+    fn symlink(&self, original: &Path, link: &Path) -> io::Result<()>;
+    /// Existence check only, not the metadata itself.
+    fn symlink_metadata(&self, path: &Path) -> io::Result<()>;
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+    /// Each entry's path plus whether it's a directory.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<(PathBuf, bool)>>;
+}
+
+pub struct RealFs;
+
+impl Fs for RealFs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+        std::fs::write(path, contents)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn symlink(&self, original: &Path, link: &Path) -> io::Result<()> {
+        std::os::unix::fs::symlink(original, link)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<()> {
+        std::fs::symlink_metadata(path).map(|_| ())
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        std::fs::read_link(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<(PathBuf, bool)>> {
+        let mut out = Vec::new();
+        for entry in std::fs::read_dir(path)?.flatten() {
+            let is_dir = entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false);
+            out.push((entry.path(), is_dir));
+        }
+        Ok(out)
+    }
+}
+
+/// In-memory fake for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeFs {
+    files: Mutex<BTreeMap<PathBuf, String>>,
+    symlinks: Mutex<BTreeMap<PathBuf, PathBuf>>,
+    dirs: Mutex<Vec<PathBuf>>,
+}
+
+#[cfg(test)]
+impl FakeFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_file(self, path: impl Into<PathBuf>, contents: impl Into<String>) -> Self {
+        self.files.lock().unwrap().insert(path.into(), contents.into());
+        self
+    }
+
+    pub fn file(&self, path: impl AsRef<Path>) -> Option<String> {
+        self.files.lock().unwrap().get(path.as_ref()).cloned()
+    }
+
+    pub fn with_symlink(self, link: impl Into<PathBuf>, target: impl Into<PathBuf>) -> Self {
+        self.symlinks.lock().unwrap().insert(link.into(), target.into());
+        self
+    }
+}
+
+#[cfg(test)]
+impl Fs for FakeFs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not found"))
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+        self.files.lock().unwrap().insert(path.to_path_buf(), contents.to_string());
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        // Bind the removed value before matching on it, so the `files` lock
+        // from the scrutinee is dropped before the `insert` below tries to
+        // take it again — matching directly on the `.lock()` call keeps the
+        // guard alive for the whole `if let` and deadlocks.
+        let moved = self.files.lock().unwrap().remove(from);
+        if let Some(contents) = moved {
+            self.files.lock().unwrap().insert(to.to_path_buf(), contents);
+        }
+        let moved_symlink = self.symlinks.lock().unwrap().remove(from);
+        if let Some(target) = moved_symlink {
+            self.symlinks.lock().unwrap().insert(to.to_path_buf(), target);
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.files.lock().unwrap().remove(path);
+        self.symlinks.lock().unwrap().remove(path);
+        Ok(())
+    }
+
+    fn symlink(&self, original: &Path, link: &Path) -> io::Result<()> {
+        self.symlinks.lock().unwrap().insert(link.to_path_buf(), original.to_path_buf());
+        Ok(())
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<()> {
+        if self.files.lock().unwrap().contains_key(path) || self.symlinks.lock().unwrap().contains_key(path) {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
+        }
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.symlinks
+            .lock()
+            .unwrap()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not found"))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<(PathBuf, bool)>> {
+        let dirs = self.dirs.lock().unwrap();
+        let files = self.files.lock().unwrap();
+        let mut out = Vec::new();
+        for dir in dirs.iter() {
+            if dir.parent() == Some(path) {
+                out.push((dir.clone(), true));
+            }
+        }
+        for file in files.keys() {
+            if file.parent() == Some(path) {
+                out.push((file.clone(), false));
+            }
+        }
+        Ok(out)
+    }
+}
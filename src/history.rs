@@ -0,0 +1,121 @@
+// Persistent EWMA latency history so mirror selection converges across runs
+// instead of flapping between near-ties.
+
+use std::{collections::HashMap, fs, path::Path, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+/// Weight given to this run's sample vs. the stored average.
+const ALPHA: f64 = 0.3;
+
+/// Extra fractional penalty applied per point of `failure_streak`.
+const FAILURE_PENALTY_PER_STREAK: f64 = 0.15;
+
+/// Cap on the penalty above.
+const MAX_FAILURE_PENALTY: f64 = 3.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct Entry {
+    ewma_millis: f64,
+    /// Counts consecutive failures; reset to 0 by the next success.
+    failure_streak: u32,
+}
+
+/// Keyed by mirror file name, the same `name` used throughout `tfr`.
+#[derive(Serialize, Deserialize, Default)]
+pub struct History(HashMap<String, Entry>);
+
+impl History {
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.0).map_err(std::io::Error::other)?;
+        // Atomic write, same pattern as update_sources_list.
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Fold this run's sample into `name`'s stored EWMA (seeding it on first
+    /// sight) and return the failure-penalized score to rank by. `sample` is
+    /// `None` when the whole probe set failed for this mirror.
+    pub fn record(&mut self, name: &str, sample: Option<Duration>) -> Duration {
+        let entry = self.0.entry(name.to_string()).or_insert(Entry {
+            ewma_millis: 0.0,
+            failure_streak: 0,
+        });
+
+        match sample {
+            Some(d) => {
+                let millis = d.as_secs_f64() * 1000.0;
+                entry.ewma_millis = if entry.ewma_millis == 0.0 {
+                    millis
+                } else {
+                    ALPHA * millis + (1.0 - ALPHA) * entry.ewma_millis
+                };
+                entry.failure_streak = 0;
+            }
+            None => entry.failure_streak += 1,
+        }
+
+        let penalty =
+            1.0 + (entry.failure_streak as f64 * FAILURE_PENALTY_PER_STREAK).min(MAX_FAILURE_PENALTY);
+        Duration::from_secs_f64(entry.ewma_millis / 1000.0 * penalty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_sample_seeds_ewma_with_no_penalty() {
+        let mut history = History::default();
+        let score = history.record("mirror", Some(Duration::from_millis(100)));
+        assert_eq!(score, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn later_sample_blends_toward_ewma() {
+        let mut history = History::default();
+        history.record("mirror", Some(Duration::from_millis(100)));
+        let score = history.record("mirror", Some(Duration::from_millis(200)));
+        // ALPHA * 200 + (1 - ALPHA) * 100 = 130ms
+        assert_eq!(score, Duration::from_millis(130));
+    }
+
+    #[test]
+    fn failure_increments_streak_and_penalizes_score() {
+        let mut history = History::default();
+        history.record("mirror", Some(Duration::from_millis(100)));
+        let score = history.record("mirror", None);
+        // failure_streak = 1 -> penalty = 1 + 0.15
+        assert_eq!(score, Duration::from_millis(115));
+    }
+
+    #[test]
+    fn success_resets_failure_streak_to_zero() {
+        let mut history = History::default();
+        history.record("mirror", Some(Duration::from_millis(100)));
+        history.record("mirror", None);
+        history.record("mirror", None);
+        let score = history.record("mirror", Some(Duration::from_millis(100)));
+        assert_eq!(score, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn failure_penalty_is_capped() {
+        let mut history = History::default();
+        history.record("mirror", Some(Duration::from_millis(100)));
+        for _ in 0..100 {
+            history.record("mirror", None);
+        }
+        let score = history.record("mirror", None);
+        assert_eq!(score, Duration::from_millis(400));
+    }
+}
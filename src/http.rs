@@ -0,0 +1,73 @@
+// Network probing abstracted behind a trait, mirroring `Fs`.
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::probe::{PhaseTimes, Resolver};
+
+#[async_trait]
+pub trait HttpProbe: Send + Sync {
+    async fn probe_phases(&self, scheme: &str, host: &str, port: u16, path: &str) -> Option<PhaseTimes>;
+    async fn probe_throughput(&self, url: &str, cap_bytes: u64, time_budget: Duration) -> Option<f64>;
+}
+
+/// `throughput_client` is `None` when throughput probing is disabled.
+pub struct RealHttpProbe {
+    pub resolver: Resolver,
+    pub throughput_client: Option<reqwest::Client>,
+}
+
+#[async_trait]
+impl HttpProbe for RealHttpProbe {
+    async fn probe_phases(&self, scheme: &str, host: &str, port: u16, path: &str) -> Option<PhaseTimes> {
+        crate::probe::probe_phases(&self.resolver, scheme, host, port, path).await
+    }
+
+    async fn probe_throughput(&self, url: &str, cap_bytes: u64, time_budget: Duration) -> Option<f64> {
+        let client = self.throughput_client.as_ref()?;
+        crate::throughput::probe_throughput(client, url, cap_bytes, time_budget).await
+    }
+}
+
+pub fn shared(resolver: Resolver, throughput_client: Option<reqwest::Client>) -> Arc<dyn HttpProbe> {
+    Arc::new(RealHttpProbe { resolver, throughput_client })
+}
+
+/// In-memory fake for tests: `probe_phases` and `probe_throughput` return
+/// pre-recorded results keyed by host and url, instead of touching the network.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeHttpProbe {
+    pub phases: std::collections::HashMap<String, PhaseTimes>,
+    pub throughput: std::collections::HashMap<String, f64>,
+}
+
+#[cfg(test)]
+impl FakeHttpProbe {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_phases(mut self, host: impl Into<String>, phases: PhaseTimes) -> Self {
+        self.phases.insert(host.into(), phases);
+        self
+    }
+
+    pub fn with_throughput(mut self, url: impl Into<String>, bytes_per_sec: f64) -> Self {
+        self.throughput.insert(url.into(), bytes_per_sec);
+        self
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl HttpProbe for FakeHttpProbe {
+    async fn probe_phases(&self, _scheme: &str, host: &str, _port: u16, _path: &str) -> Option<PhaseTimes> {
+        self.phases.get(host).copied()
+    }
+
+    async fn probe_throughput(&self, url: &str, _cap_bytes: u64, _time_budget: Duration) -> Option<f64> {
+        self.throughput.get(url).copied()
+    }
+}
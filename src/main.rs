@@ -1,23 +1,52 @@
-use futures::stream::{FuturesUnordered, StreamExt};
-use reqwest::Client;
+mod daemon;
+mod fs;
+mod history;
+mod http;
+mod probe;
+mod throughput;
+
 use std::{
-    fs,
     path::{Path, PathBuf},
+    sync::Arc,
     time::{Duration, Instant},
 };
 
+use futures::stream::{FuturesUnordered, StreamExt};
+use reqwest::{Client, Url};
+
+use fs::{Fs, RealFs};
+use history::History;
+use http::HttpProbe;
+use probe::PhaseTimes;
+
 const MIRROR_DIR: &str = "/data/data/com.termux/files/usr/etc/termux/mirrors";
 const LINK_PATH: &str = "/data/data/com.termux/files/usr/etc/termux/chosen_mirrors";
 const SOURCES_LIST: &str = "/data/data/com.termux/files/usr/etc/apt/sources.list";
 const SOURCES_BACKUP: &str = "/data/data/com.termux/files/usr/etc/apt/sources.list.bak";
+const HISTORY_PATH: &str = "/data/data/com.termux/files/usr/etc/termux/tfr_history.json";
 const PROBE_SUFFIX: &str = "dists/stable/Release";
 const SAMPLES: usize = 3;
 
+// Target busy-fraction for the daemon tranquilizer.
+const DEFAULT_BUSY_FRACTION: f64 = 0.2;
+
+// Upper bound on the tranquilizer's computed sleep.
+const MAX_TRANQUILIZER_SLEEP: Duration = Duration::from_secs(30 * 60);
+
+// Same file the latency phase already fetches; point --throughput-path at a
+// Packages index for a bigger sample.
+const DEFAULT_THROUGHPUT_PATH: &str = PROBE_SUFFIX;
+const DEFAULT_THROUGHPUT_CAP_BYTES: u64 = 300_000;
+const DEFAULT_THROUGHPUT_TIME_BUDGET: Duration = Duration::from_secs(2);
+
 struct Mirror {
     path: PathBuf,
     name: String,
-    base_url: String,  // e.g. https://mirror.sunred.org/termux/termux-main
-    probe_url: String, // base_url + "/" + PROBE_SUFFIX
+    base_url: String, // e.g. https://mirror.sunred.org/termux/termux-main
+    scheme: String,
+    host: String,
+    port: u16,
+    probe_path: String, // path (+ query) of base_url + "/" + PROBE_SUFFIX, for the raw HEAD request
 }
 
 struct BenchResult {
@@ -26,48 +55,192 @@ struct BenchResult {
     base_url: String,
     avg_latency: Duration,
     jitter: Duration,
+    dns: Duration,
+    connect: Duration,
+    tls_handshake: Duration,
+    ttfb: Duration,
+    // Failure-penalized EWMA score from History, used to rank instead of avg_latency.
+    score: Duration,
+    // Bytes/sec from the throughput phase; None when disabled or the ranged GET failed.
+    throughput: Option<f64>,
 }
 
-fn collect_mirrors(dir: &Path) -> Vec<PathBuf> {
-    let mut files = Vec::new();
-    if let Ok(entries) = fs::read_dir(dir) {
-        for entry in entries.flatten() {
-            let Ok(ft) = entry.file_type() else { continue };
-            let path = entry.path();
-            if ft.is_dir() {
-                files.extend(collect_mirrors(&path));
-            } else if !path.to_str().unwrap_or("").contains(".dpkg-") {
-                files.push(path);
+// Which column to rank mirrors by. Total (the default) ranks by overall latency, via the History score.
+#[derive(Clone, Copy, PartialEq)]
+enum SortKey {
+    Total,
+    Dns,
+    Connect,
+    TlsHandshake,
+    Ttfb,
+}
+
+impl SortKey {
+    fn from_arg(s: &str) -> Option<Self> {
+        match s {
+            "total" => Some(Self::Total),
+            "dns" => Some(Self::Dns),
+            "connect" => Some(Self::Connect),
+            "tls" | "tls_handshake" => Some(Self::TlsHandshake),
+            "ttfb" => Some(Self::Ttfb),
+            _ => None,
+        }
+    }
+
+    fn of(self, r: &BenchResult) -> Duration {
+        match self {
+            // Rank by the persistent, failure-penalized EWMA rather than
+            // this run's raw average so a single noisy sample can't flap
+            // the chosen mirror.
+            Self::Total => r.score,
+            Self::Dns => r.dns,
+            Self::Connect => r.connect,
+            Self::TlsHandshake => r.tls_handshake,
+            Self::Ttfb => r.ttfb,
+        }
+    }
+}
+
+// Parsed command-line configuration for one invocation of tfr.
+struct Config {
+    sort_key: SortKey,
+    daemon: bool,
+    // Floor on the gap between probe batches; the tranquilizer can only stretch it further.
+    interval: Duration,
+    busy_fraction: f64,
+    // Rank by the latency+bandwidth composite instead of latency alone.
+    throughput: bool,
+    throughput_path: String,
+    throughput_cap_bytes: u64,
+    throughput_time_budget: Duration,
+    weight_latency: f64,
+    weight_bandwidth: f64,
+    // Print what would change without touching the symlink or sources.list.
+    dry_run: bool,
+}
+
+impl Config {
+    fn from_args() -> Self {
+        let mut sort_key = SortKey::Total;
+        let mut daemon = false;
+        // Below MAX_TRANQUILIZER_SLEEP so the tranquilizer can still stretch the
+        // gap past it; with the default busy fraction that takes a batch over 150s.
+        let mut interval = Duration::from_secs(600);
+        let mut busy_fraction = DEFAULT_BUSY_FRACTION;
+        let mut throughput = false;
+        let mut throughput_path = DEFAULT_THROUGHPUT_PATH.to_string();
+        let mut throughput_cap_bytes = DEFAULT_THROUGHPUT_CAP_BYTES;
+        let mut throughput_time_budget = DEFAULT_THROUGHPUT_TIME_BUDGET;
+        let mut weight_latency = 0.5;
+        let mut weight_bandwidth = 0.5;
+        let mut dry_run = false;
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--sort-by" => match args.next().as_deref().and_then(SortKey::from_arg) {
+                    Some(key) => sort_key = key,
+                    None => eprintln!("Unknown --sort-by value, using total"),
+                },
+                "--daemon" => daemon = true,
+                "--interval" => match args.next().and_then(|v| v.parse().ok()) {
+                    Some(secs) => interval = Duration::from_secs(secs),
+                    None => eprintln!("Invalid --interval value, using {}s", interval.as_secs()),
+                },
+                "--busy-fraction" => match args.next().and_then(|v| v.parse().ok()) {
+                    Some(p) => busy_fraction = p,
+                    None => eprintln!("Invalid --busy-fraction value, using {busy_fraction}"),
+                },
+                "--throughput" => throughput = true,
+                "--throughput-path" => {
+                    if let Some(p) = args.next() {
+                        throughput_path = p;
+                    }
+                }
+                "--throughput-cap-kb" => match args.next().and_then(|v| v.parse::<u64>().ok()) {
+                    Some(kb) => throughput_cap_bytes = kb * 1_000,
+                    None => eprintln!("Invalid --throughput-cap-kb value, using default"),
+                },
+                "--throughput-time-budget" => match args.next().and_then(|v| v.parse().ok()) {
+                    Some(secs) => throughput_time_budget = Duration::from_secs(secs),
+                    None => eprintln!("Invalid --throughput-time-budget value, using default"),
+                },
+                "--weight-latency" => match args.next().and_then(|v| v.parse().ok()) {
+                    Some(w) => weight_latency = w,
+                    None => eprintln!("Invalid --weight-latency value, using default"),
+                },
+                "--weight-bandwidth" => match args.next().and_then(|v| v.parse().ok()) {
+                    Some(w) => weight_bandwidth = w,
+                    None => eprintln!("Invalid --weight-bandwidth value, using default"),
+                },
+                "--dry-run" => dry_run = true,
+                _ => {}
             }
         }
+
+        Config {
+            sort_key,
+            daemon,
+            interval,
+            busy_fraction,
+            throughput,
+            throughput_path,
+            throughput_cap_bytes,
+            throughput_time_budget,
+            weight_latency,
+            weight_bandwidth,
+            dry_run,
+        }
     }
-    files
 }
 
-async fn probe_mirror(client: &Client, probe_url: &str) -> Option<Duration> {
-    let start = Instant::now();
-    let resp = client.head(probe_url).send().await.ok()?;
-    if resp.status().is_success() {
-        Some(start.elapsed())
+// Min-max normalize into [0, 1]; a degenerate batch (every mirror tied) normalizes to 0.
+fn normalize(value: f64, min: f64, max: f64) -> f64 {
+    if max > min {
+        (value - min) / (max - min)
     } else {
-        None
+        0.0
     }
 }
 
-fn update_sources_list(base_url: &str) -> std::io::Result<()> {
-    let original = match fs::read_to_string(SOURCES_LIST) {
-        Ok(s) => s,
-        // sources.list missing entirely — nothing to update
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
-        Err(e) => return Err(e),
-    };
+fn min_max(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+        (min.min(v), max.max(v))
+    })
+}
 
-    // One-time backup: only create if no backup exists yet, preserving factory state
-    if fs::symlink_metadata(SOURCES_BACKUP).is_err() {
-        fs::write(SOURCES_BACKUP, &original)?;
-        println!("Backup: saved original sources.list to sources.list.bak");
+// w_lat * lat_norm + w_bw * (1 - bw_norm); lower is better. No throughput
+// sample is treated as bw_norm = 0, the worst case.
+fn composite_score(
+    r: &BenchResult,
+    lat_min: f64,
+    lat_max: f64,
+    bw_min: f64,
+    bw_max: f64,
+    config: &Config,
+) -> f64 {
+    let lat_norm = normalize(r.avg_latency.as_secs_f64(), lat_min, lat_max);
+    let bw_norm = r.throughput.map_or(0.0, |bw| normalize(bw, bw_min, bw_max));
+    config.weight_latency * lat_norm + config.weight_bandwidth * (1.0 - bw_norm)
+}
+
+fn collect_mirrors(fs: &dyn Fs, dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Ok(entries) = fs.read_dir(dir) {
+        for (path, is_dir) in entries {
+            if is_dir {
+                files.extend(collect_mirrors(fs, &path));
+            } else if !path.to_str().unwrap_or("").contains(".dpkg-") {
+                files.push(path);
+            }
+        }
     }
+    files
+}
 
+// Rewritten sources.list contents for base_url, or None if no recognizable
+// termux-main line was found to replace.
+fn rewrite_sources_list(original: &str, base_url: &str) -> Option<String> {
     let new_line = format!("deb {} stable main", base_url);
     let mut replaced = false;
 
@@ -89,16 +262,39 @@ fn update_sources_list(base_url: &str) -> std::io::Result<()> {
         .collect::<Vec<_>>()
         .join("\n");
 
-    if !replaced {
+    replaced.then(|| format!("{}\n", new_contents))
+}
+
+fn update_sources_list(fs: &dyn Fs, base_url: &str, dry_run: bool) -> std::io::Result<()> {
+    let original = match fs.read_to_string(Path::new(SOURCES_LIST)) {
+        Ok(s) => s,
+        // sources.list missing entirely — nothing to update
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let Some(new_contents) = rewrite_sources_list(&original, base_url) else {
         // No recognisable termux-main line found — do not touch the file
         println!("Note: no termux-main line found in sources.list, skipping rewrite");
         return Ok(());
+    };
+
+    if dry_run {
+        let new_line = format!("deb {} stable main", base_url);
+        println!("[dry-run] would update sources.list line to: {new_line}");
+        return Ok(());
+    }
+
+    // One-time backup: only create if no backup exists yet, preserving factory state
+    if fs.symlink_metadata(Path::new(SOURCES_BACKUP)).is_err() {
+        fs.write(Path::new(SOURCES_BACKUP), &original)?;
+        println!("Backup: saved original sources.list to sources.list.bak");
     }
 
     // Atomic write: temp file + rename so a killed process cannot corrupt sources.list
     let tmp = format!("{}.tmp", SOURCES_LIST);
-    fs::write(&tmp, format!("{}\n", new_contents))?;
-    fs::rename(&tmp, SOURCES_LIST)?;
+    fs.write(Path::new(&tmp), &new_contents)?;
+    fs.rename(Path::new(&tmp), Path::new(SOURCES_LIST))?;
 
     println!("sources.list: updated to {}", base_url);
     Ok(())
@@ -106,18 +302,48 @@ fn update_sources_list(base_url: &str) -> std::io::Result<()> {
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    let client = Client::builder()
-        .timeout(Duration::from_secs(3))
-        .tcp_nodelay(true)
-        .build()
-        .unwrap();
+    let config = Config::from_args();
+    let fs: Arc<dyn Fs> = Arc::new(RealFs);
+    let throughput_client = config.throughput.then(|| {
+        Client::builder()
+            .timeout(config.throughput_time_budget + Duration::from_secs(1))
+            .build()
+            .unwrap()
+    });
+    let http = http::shared(probe::build_resolver(), throughput_client);
+    let mut history = History::load(Path::new(HISTORY_PATH));
+
+    loop {
+        let batch_start = Instant::now();
+        run_once(&config, fs.as_ref(), &http, &mut history).await?;
 
-    let paths = collect_mirrors(Path::new(MIRROR_DIR));
+        if !config.daemon {
+            return Ok(());
+        }
+
+        let sleep = daemon::tranquilizer_sleep(
+            batch_start.elapsed(),
+            config.busy_fraction,
+            MAX_TRANQUILIZER_SLEEP,
+        )
+        .max(config.interval);
+        println!("\nDaemon: sleeping {sleep:?} before the next probe batch\n");
+        tokio::time::sleep(sleep).await;
+    }
+}
+
+async fn run_once(
+    config: &Config,
+    fs: &dyn Fs,
+    http: &Arc<dyn HttpProbe>,
+    history: &mut History,
+) -> std::io::Result<()> {
+    let paths = collect_mirrors(fs, Path::new(MIRROR_DIR));
 
     let mirrors: Vec<Mirror> = paths
         .into_iter()
         .filter_map(|path| {
-            let content = fs::read_to_string(&path).ok()?;
+            let content = fs.read_to_string(&path).ok()?;
             let raw = content
                 .lines()
                 .find(|l| l.starts_with("MAIN="))?
@@ -127,7 +353,18 @@ async fn main() -> std::io::Result<()> {
             let base_url = raw.trim_end_matches('/').to_string();
             let probe_url = format!("{}/{}", base_url, PROBE_SUFFIX);
             let name = path.file_name()?.to_string_lossy().into();
-            Some(Mirror { path, name, base_url, probe_url })
+
+            let parsed = Url::parse(&probe_url).ok()?;
+            let scheme = parsed.scheme().to_string();
+            let host = parsed.host_str()?.to_string();
+            let port = parsed.port_or_known_default()?;
+            let probe_path = if parsed.query().is_some() {
+                format!("{}?{}", parsed.path(), parsed.query().unwrap())
+            } else {
+                parsed.path().to_string()
+            };
+
+            Some(Mirror { path, name, base_url, scheme, host, port, probe_path })
         })
         .collect();
 
@@ -140,65 +377,358 @@ async fn main() -> std::io::Result<()> {
     let mut tasks: FuturesUnordered<_> = mirrors
         .into_iter()
         .map(|m| {
-            let client = client.clone();
+            let http = Arc::clone(http);
+            let throughput = config.throughput;
+            let throughput_url = format!("{}/{}", m.base_url, config.throughput_path);
+            let throughput_cap_bytes = config.throughput_cap_bytes;
+            let throughput_time_budget = config.throughput_time_budget;
             tokio::spawn(async move {
-                let mut latencies = Vec::with_capacity(SAMPLES);
+                let mut samples = Vec::with_capacity(SAMPLES);
                 for _ in 0..SAMPLES {
-                    if let Some(l) = probe_mirror(&client, &m.probe_url).await {
-                        latencies.push(l);
+                    if let Some(phases) =
+                        http.probe_phases(&m.scheme, &m.host, m.port, &m.probe_path).await
+                    {
+                        samples.push(phases);
                     }
                 }
-                if latencies.len() == SAMPLES {
-                    let sum: Duration = latencies.iter().copied().sum();
-                    let avg = sum / SAMPLES as u32;
-                    let jitter =
-                        latencies.iter().copied().max()? - latencies.iter().copied().min()?;
-                    Some(BenchResult {
-                        path: m.path,
-                        name: m.name,
-                        base_url: m.base_url,
-                        avg_latency: avg,
-                        jitter,
-                    })
+                let avg = if samples.len() == SAMPLES {
+                    let totals: Vec<Duration> = samples.iter().map(PhaseTimes::total).collect();
+                    let n = SAMPLES as u32;
+                    Some((
+                        totals.iter().sum::<Duration>() / n,
+                        totals.iter().copied().max().unwrap() - totals.iter().copied().min().unwrap(),
+                        samples.iter().map(|p| p.dns).sum::<Duration>() / n,
+                        samples.iter().map(|p| p.connect).sum::<Duration>() / n,
+                        samples.iter().map(|p| p.tls_handshake).sum::<Duration>() / n,
+                        samples.iter().map(|p| p.ttfb).sum::<Duration>() / n,
+                    ))
                 } else {
                     None
-                }
+                };
+
+                let bw = if throughput && avg.is_some() {
+                    http.probe_throughput(&throughput_url, throughput_cap_bytes, throughput_time_budget)
+                        .await
+                } else {
+                    None
+                };
+
+                (m.path, m.name, m.base_url, avg, bw)
             })
         })
         .collect();
 
+    // Fold every outcome (success or failure) into the persistent history so
+    // a mirror's flakiness is remembered even on a run where it's skipped.
     let mut results = Vec::new();
     while let Some(res) = tasks.next().await {
-        if let Ok(Some(r)) = res {
-            results.push(r);
+        let Ok((path, name, base_url, avg, throughput)) = res else { continue };
+        let score = history.record(&name, avg.map(|(avg_latency, ..)| avg_latency));
+        if let Some((avg_latency, jitter, dns, connect, tls_handshake, ttfb)) = avg {
+            results.push(BenchResult {
+                path,
+                name,
+                base_url,
+                avg_latency,
+                jitter,
+                dns,
+                connect,
+                tls_handshake,
+                ttfb,
+                score,
+                throughput,
+            });
         }
     }
 
-    results.sort_unstable_by(|a, b| {
-        a.avg_latency
-            .cmp(&b.avg_latency)
-            .then(a.jitter.cmp(&b.jitter))
-    });
+    if config.throughput {
+        // lat_min/lat_max must span every result, not just the ones with a
+        // throughput sample — composite_score is called for all of them, and
+        // a narrower range would put latency-only mirrors outside [0, 1].
+        let (lat_min, lat_max) = min_max(results.iter().map(|r| r.avg_latency.as_secs_f64()));
+        let (bw_min, bw_max) = min_max(results.iter().filter_map(|r| r.throughput));
+        results.sort_unstable_by(|a, b| {
+            composite_score(a, lat_min, lat_max, bw_min, bw_max, config)
+                .total_cmp(&composite_score(b, lat_min, lat_max, bw_min, bw_max, config))
+        });
+    } else {
+        results.sort_unstable_by(|a, b| {
+            config
+                .sort_key
+                .of(a)
+                .cmp(&config.sort_key.of(b))
+                .then(a.jitter.cmp(&b.jitter))
+        });
+    }
 
-    println!("\n{:<25} | {:<12} | {:<10}", "MIRROR", "AVG LATENCY", "JITTER");
-    println!("{:-<52}", "");
+    if config.throughput {
+        println!(
+            "\n{:<25} | {:<10} | {:<10} | {:<10} | {:<10} | {:<12}",
+            "MIRROR", "DNS", "CONNECT", "TLS", "TTFB", "THROUGHPUT"
+        );
+    } else {
+        println!(
+            "\n{:<25} | {:<10} | {:<10} | {:<10} | {:<10} | {:<10}",
+            "MIRROR", "DNS", "CONNECT", "TLS", "TTFB", "JITTER"
+        );
+    }
+    println!("{:-<85}", "");
     for r in results.iter().take(10) {
-        println!("{:<25} | {:<12?} | {:<10?}", r.name, r.avg_latency, r.jitter);
+        if config.throughput {
+            let bw = r
+                .throughput
+                .map(|b| format!("{:.0} KB/s", b / 1_000.0))
+                .unwrap_or_else(|| "n/a".to_string());
+            println!(
+                "{:<25} | {:<10?} | {:<10?} | {:<10?} | {:<10?} | {:<12}",
+                r.name, r.dns, r.connect, r.tls_handshake, r.ttfb, bw
+            );
+        } else {
+            println!(
+                "{:<25} | {:<10?} | {:<10?} | {:<10?} | {:<10?} | {:<10?}",
+                r.name, r.dns, r.connect, r.tls_handshake, r.ttfb, r.jitter
+            );
+        }
     }
 
     if let Some(best) = results.first() {
-        // Update the symlink
-        if fs::symlink_metadata(LINK_PATH).is_ok() {
-            fs::remove_file(LINK_PATH)?;
-        }
-        std::os::unix::fs::symlink(&best.path, LINK_PATH)?;
-        println!("Symlink: chosen_mirrors -> {}", best.name);
+        let link_path = Path::new(LINK_PATH);
+
+        // If the symlink already points at this mirror, leave it and
+        // sources.list alone — nothing changed, nothing to rewrite.
+        if fs.read_link(link_path).ok().as_deref() == Some(best.path.as_path()) {
+            println!("\nUnchanged: {} is still the active mirror", best.name);
+        } else if config.dry_run {
+            println!("[dry-run] would symlink chosen_mirrors -> {}", best.name);
+            update_sources_list(fs, &best.base_url, true)?;
+            println!("\n[dry-run] {} would become the active mirror", best.name);
+        } else {
+            // Create the new symlink at a temp path, then rename over
+            // LINK_PATH. A prior run that crashed between these two calls
+            // can leave the tmp path behind, so clear it first.
+            let tmp_link = format!("{LINK_PATH}.tmp");
+            let _ = fs.remove_file(Path::new(&tmp_link));
+            fs.symlink(&best.path, Path::new(&tmp_link))?;
+            fs.rename(Path::new(&tmp_link), link_path)?;
+            println!("Symlink: chosen_mirrors -> {}", best.name);
 
-        // Surgically update sources.list to prevent pkg from bypassing the mirror
-        update_sources_list(&best.base_url)?;
+            // Surgically update sources.list to prevent pkg from bypassing the mirror
+            update_sources_list(fs, &best.base_url, false)?;
 
-        println!("\nSUCCESS: {} is now the active mirror", best.name);
+            println!("\nSUCCESS: {} is now the active mirror", best.name);
+        }
+    }
+
+    if !config.dry_run {
+        if let Err(e) = history.save(Path::new(HISTORY_PATH)) {
+            eprintln!("Warning: could not save mirror history: {e}");
+        }
     }
 
     Ok(())
-      }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::FakeFs;
+    use http::FakeHttpProbe;
+
+    fn test_config(throughput: bool) -> Config {
+        Config {
+            sort_key: SortKey::Total,
+            daemon: false,
+            interval: Duration::from_secs(600),
+            busy_fraction: DEFAULT_BUSY_FRACTION,
+            throughput,
+            throughput_path: DEFAULT_THROUGHPUT_PATH.to_string(),
+            throughput_cap_bytes: DEFAULT_THROUGHPUT_CAP_BYTES,
+            throughput_time_budget: DEFAULT_THROUGHPUT_TIME_BUDGET,
+            weight_latency: 0.5,
+            weight_bandwidth: 0.5,
+            dry_run: false,
+        }
+    }
+
+    fn mirror_file(name: &str, base_url: &str) -> (PathBuf, String) {
+        (
+            PathBuf::from(format!("{MIRROR_DIR}/{name}")),
+            format!("MAIN=\"{base_url}\"\n"),
+        )
+    }
+
+    #[test]
+    fn rewrites_termux_main_line() {
+        let original = "deb https://old.example/termux/termux-main stable main\n";
+        let new = rewrite_sources_list(original, "https://new.example/termux/termux-main").unwrap();
+        assert_eq!(new, "deb https://new.example/termux/termux-main stable main\n");
+    }
+
+    #[test]
+    fn rewrites_packages_cf_cdn_line() {
+        let original = "deb https://packages-cf.termux.dev/apt/termux-main stable main\n";
+        let new = rewrite_sources_list(original, "https://mirror.example/termux/termux-main").unwrap();
+        assert_eq!(new, "deb https://mirror.example/termux/termux-main stable main\n");
+    }
+
+    #[test]
+    fn rewrites_packages_termux_dev_line() {
+        let original = "deb https://packages.termux.dev/apt/termux-main stable main\n";
+        let new = rewrite_sources_list(original, "https://mirror.example/termux/termux-main").unwrap();
+        assert_eq!(new, "deb https://mirror.example/termux/termux-main stable main\n");
+    }
+
+    #[test]
+    fn skips_when_no_recognizable_line() {
+        let original = "deb https://example.com/some-other-repo stable main\n";
+        assert!(rewrite_sources_list(original, "https://mirror.example/termux/termux-main").is_none());
+    }
+
+    #[test]
+    fn update_sources_list_writes_backup_once_and_rewrites_in_place() {
+        let fake = FakeFs::new().with_file(
+            SOURCES_LIST,
+            "deb https://old.example/termux/termux-main stable main\n",
+        );
+
+        update_sources_list(&fake, "https://new.example/termux/termux-main", false).unwrap();
+
+        assert_eq!(
+            fake.file(SOURCES_LIST).unwrap(),
+            "deb https://new.example/termux/termux-main stable main\n"
+        );
+        assert!(fake.file(SOURCES_BACKUP).is_some());
+
+        // A second run with a different mirror must not overwrite the backup.
+        let backup_after_first = fake.file(SOURCES_BACKUP).unwrap();
+        update_sources_list(&fake, "https://third.example/termux/termux-main", false).unwrap();
+        assert_eq!(fake.file(SOURCES_BACKUP).unwrap(), backup_after_first);
+    }
+
+    #[test]
+    fn update_sources_list_dry_run_does_not_mutate() {
+        let original = "deb https://old.example/termux/termux-main stable main\n";
+        let fake = FakeFs::new().with_file(SOURCES_LIST, original);
+
+        update_sources_list(&fake, "https://new.example/termux/termux-main", true).unwrap();
+
+        assert_eq!(fake.file(SOURCES_LIST).unwrap(), original);
+        assert!(fake.file(SOURCES_BACKUP).is_none());
+    }
+
+    #[test]
+    fn update_sources_list_skips_unrecognized_file() {
+        let original = "deb https://example.com/some-other-repo stable main\n";
+        let fake = FakeFs::new().with_file(SOURCES_LIST, original);
+
+        update_sources_list(&fake, "https://new.example/termux/termux-main", false).unwrap();
+
+        // Unchanged, and no backup taken since nothing was rewritten.
+        assert_eq!(fake.file(SOURCES_LIST).unwrap(), original);
+        assert!(fake.file(SOURCES_BACKUP).is_none());
+    }
+
+    #[tokio::test]
+    async fn run_once_symlinks_the_fastest_scoring_mirror() {
+        let (slow_path, slow_contents) = mirror_file("slow", "https://slow.example/termux/termux-main");
+        let (fast_path, fast_contents) = mirror_file("fast", "https://fast.example/termux/termux-main");
+        let fake_fs = FakeFs::new()
+            .with_file(slow_path.clone(), slow_contents)
+            .with_file(fast_path.clone(), fast_contents);
+
+        let fast_phases = PhaseTimes {
+            dns: Duration::from_millis(1),
+            connect: Duration::from_millis(1),
+            tls_handshake: Duration::from_millis(1),
+            ttfb: Duration::from_millis(1),
+        };
+        let slow_phases = PhaseTimes {
+            dns: Duration::from_millis(200),
+            connect: Duration::from_millis(200),
+            tls_handshake: Duration::from_millis(200),
+            ttfb: Duration::from_millis(200),
+        };
+        let http: Arc<dyn HttpProbe> = Arc::new(
+            FakeHttpProbe::new()
+                .with_phases("fast.example", fast_phases)
+                .with_phases("slow.example", slow_phases),
+        );
+
+        let config = test_config(false);
+        let mut history = History::default();
+        run_once(&config, &fake_fs, &http, &mut history).await.unwrap();
+
+        assert_eq!(fake_fs.read_link(Path::new(LINK_PATH)).unwrap(), fast_path);
+    }
+
+    #[tokio::test]
+    async fn run_once_prefers_higher_throughput_when_bandwidth_is_weighted_heavily() {
+        let (lat_path, lat_contents) = mirror_file("low-lat", "https://low-lat.example/termux/termux-main");
+        let (bw_path, bw_contents) = mirror_file("high-bw", "https://high-bw.example/termux/termux-main");
+        let fake_fs = FakeFs::new()
+            .with_file(lat_path.clone(), lat_contents)
+            .with_file(bw_path.clone(), bw_contents);
+
+        let fast_phases = PhaseTimes {
+            dns: Duration::from_millis(1),
+            connect: Duration::from_millis(1),
+            tls_handshake: Duration::from_millis(1),
+            ttfb: Duration::from_millis(1),
+        };
+        let slow_phases = PhaseTimes {
+            dns: Duration::from_millis(50),
+            connect: Duration::from_millis(50),
+            tls_handshake: Duration::from_millis(50),
+            ttfb: Duration::from_millis(50),
+        };
+        let http: Arc<dyn HttpProbe> = Arc::new(
+            FakeHttpProbe::new()
+                .with_phases("low-lat.example", fast_phases)
+                .with_phases("high-bw.example", slow_phases)
+                .with_throughput("https://low-lat.example/termux/termux-main/dists/stable/Release", 1_000.0)
+                .with_throughput("https://high-bw.example/termux/termux-main/dists/stable/Release", 1_000_000_000.0),
+        );
+
+        let mut config = test_config(true);
+        config.weight_latency = 0.2;
+        config.weight_bandwidth = 0.8;
+        let mut history = History::default();
+        run_once(&config, &fake_fs, &http, &mut history).await.unwrap();
+
+        // low-lat.example is faster but far slower to transfer; with bandwidth
+        // weighted heavily the composite score should still pick high-bw.
+        assert_eq!(fake_fs.read_link(Path::new(LINK_PATH)).unwrap(), bw_path);
+    }
+
+    #[tokio::test]
+    async fn run_once_skips_sources_list_rewrite_when_mirror_unchanged() {
+        let (fast_path, fast_contents) = mirror_file("fast", "https://fast.example/termux/termux-main");
+        let fake_fs = FakeFs::new()
+            .with_file(fast_path.clone(), fast_contents)
+            .with_file(
+                SOURCES_LIST,
+                "deb https://old.example/termux/termux-main stable main\n",
+            )
+            .with_symlink(LINK_PATH, fast_path.clone());
+
+        let phases = PhaseTimes {
+            dns: Duration::from_millis(1),
+            connect: Duration::from_millis(1),
+            tls_handshake: Duration::from_millis(1),
+            ttfb: Duration::from_millis(1),
+        };
+        let http: Arc<dyn HttpProbe> =
+            Arc::new(FakeHttpProbe::new().with_phases("fast.example", phases));
+
+        let config = test_config(false);
+        let mut history = History::default();
+        run_once(&config, &fake_fs, &http, &mut history).await.unwrap();
+
+        // Already the active mirror: sources.list must be left untouched, no backup taken.
+        assert_eq!(
+            fake_fs.file(SOURCES_LIST).unwrap(),
+            "deb https://old.example/termux/termux-main stable main\n"
+        );
+        assert!(fake_fs.file(SOURCES_BACKUP).is_none());
+    }
+}
@@ -0,0 +1,137 @@
+// Phased latency probing: split a mirror probe into DNS, TCP-connect,
+// TLS-handshake, and time-to-first-byte.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use hickory_resolver::{
+    config::{ResolverConfig, ResolverOpts},
+    TokioAsyncResolver,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+use tokio_rustls::{rustls, TlsConnector};
+
+/// Per-stage timeout, matching the 3s the old `Client` used for the whole request.
+const STAGE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Wall-clock time spent in each stage of reaching a mirror.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PhaseTimes {
+    pub dns: Duration,
+    pub connect: Duration,
+    pub tls_handshake: Duration,
+    pub ttfb: Duration,
+}
+
+impl PhaseTimes {
+    pub fn total(&self) -> Duration {
+        self.dns + self.connect + self.tls_handshake + self.ttfb
+    }
+}
+
+pub type Resolver = TokioAsyncResolver;
+
+/// Resolver using hickory's built-in default config (Google public DNS), not
+/// the system's resolv.conf; shared across all probes in a run.
+pub fn build_resolver() -> TokioAsyncResolver {
+    TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+}
+
+fn tls_connector() -> &'static TlsConnector {
+    static CONNECTOR: OnceLock<TlsConnector> = OnceLock::new();
+    CONNECTOR.get_or_init(|| {
+        let mut root_store = rustls::RootCertStore::empty();
+        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let config = rustls::ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+        TlsConnector::from(std::sync::Arc::new(config))
+    })
+}
+
+async fn resolve_host(resolver: &TokioAsyncResolver, host: &str) -> Option<IpAddr> {
+    // A bare IP literal (some mirrors are configured that way) needs no lookup.
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    resolver.lookup_ip(host).await.ok()?.iter().next()
+}
+
+fn looks_like_success_status(buf: &[u8]) -> bool {
+    let line = String::from_utf8_lossy(buf);
+    line.starts_with("HTTP/1.1 2") || line.starts_with("HTTP/1.0 2")
+}
+
+/// Read until we have a full status line or the buffer fills — a single
+/// `read()` can return a short chunk and split the status line across reads.
+async fn read_status_line<S: tokio::io::AsyncRead + Unpin>(stream: &mut S) -> Option<[u8; 16]> {
+    let mut buf = [0u8; 16];
+    let mut filled = 0;
+    while filled < buf.len() && !buf[..filled].windows(2).any(|w| w == b"\r\n") {
+        let n = stream.read(&mut buf[filled..]).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Some(buf)
+}
+
+/// Resolve `host`, connect, complete the TLS handshake (if `scheme` is
+/// `"https"`), and issue a raw HEAD request for `path`, timing each stage.
+/// Each stage is bounded by `STAGE_TIMEOUT`; returns `None` on failure,
+/// timeout, or a non-2xx response.
+pub async fn probe_phases(
+    resolver: &TokioAsyncResolver,
+    scheme: &str,
+    host: &str,
+    port: u16,
+    path: &str,
+) -> Option<PhaseTimes> {
+    let dns_start = Instant::now();
+    let ip = timeout(STAGE_TIMEOUT, resolve_host(resolver, host)).await.ok()??;
+    let dns = dns_start.elapsed();
+
+    let connect_start = Instant::now();
+    let stream = timeout(STAGE_TIMEOUT, TcpStream::connect(SocketAddr::new(ip, port)))
+        .await
+        .ok()?
+        .ok()?;
+    let connect = connect_start.elapsed();
+
+    let request =
+        format!("HEAD {path} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\nUser-Agent: tfr/0.1\r\n\r\n");
+
+    if scheme == "https" {
+        let server_name = rustls::pki_types::ServerName::try_from(host.to_string()).ok()?;
+        let tls_start = Instant::now();
+        let mut tls_stream = timeout(STAGE_TIMEOUT, tls_connector().connect(server_name, stream))
+            .await
+            .ok()?
+            .ok()?;
+        let tls_handshake = tls_start.elapsed();
+
+        let ttfb_start = Instant::now();
+        timeout(STAGE_TIMEOUT, tls_stream.write_all(request.as_bytes())).await.ok()?.ok()?;
+        let status_buf = timeout(STAGE_TIMEOUT, read_status_line(&mut tls_stream)).await.ok()??;
+        let ttfb = ttfb_start.elapsed();
+
+        looks_like_success_status(&status_buf).then_some(PhaseTimes { dns, connect, tls_handshake, ttfb })
+    } else {
+        let mut stream = stream;
+        let ttfb_start = Instant::now();
+        timeout(STAGE_TIMEOUT, stream.write_all(request.as_bytes())).await.ok()?.ok()?;
+        let status_buf = timeout(STAGE_TIMEOUT, read_status_line(&mut stream)).await.ok()??;
+        let ttfb = ttfb_start.elapsed();
+
+        looks_like_success_status(&status_buf)
+            .then_some(PhaseTimes { dns, connect, tls_handshake: Duration::ZERO, ttfb })
+    }
+}
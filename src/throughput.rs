@@ -0,0 +1,51 @@
+// Optional throughput phase: a ranged GET measuring actual bytes/sec.
+
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use reqwest::{header::RANGE, Client};
+
+/// Issue a ranged GET for `url`, capping how much is read at `cap_bytes` and
+/// how long at `time_budget` — whichever hits first ends the measurement.
+/// Returns `None` if the request fails outright or nothing was read.
+pub async fn probe_throughput(
+    client: &Client,
+    url: &str,
+    cap_bytes: u64,
+    time_budget: Duration,
+) -> Option<f64> {
+    let resp = client
+        .get(url)
+        .header(RANGE, format!("bytes=0-{}", cap_bytes.saturating_sub(1)))
+        .send()
+        .await
+        .ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+
+    let start = Instant::now();
+    let mut stream = resp.bytes_stream();
+    let mut read = 0u64;
+
+    loop {
+        let remaining = time_budget.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            break;
+        }
+        let Ok(Some(chunk)) = tokio::time::timeout(remaining, stream.next()).await else {
+            break;
+        };
+        let Ok(chunk) = chunk else { break };
+        read += chunk.len() as u64;
+        if read >= cap_bytes {
+            break;
+        }
+    }
+
+    if read == 0 {
+        return None;
+    }
+    let elapsed = start.elapsed().max(Duration::from_millis(1));
+    Some(read as f64 / elapsed.as_secs_f64())
+}